@@ -6,15 +6,26 @@
 //! import into spreadsheet software like Microsoft Excel. It handles optional '# Time'
 //! headers by carrying forward the last seen time value.
 
+use arrow::array::{
+    ArrayRef, Float64Builder, StringBuilder, StringDictionaryBuilder, UInt64Builder,
+};
+use arrow::datatypes::{DataType, Field, Int32Type, Schema};
+use arrow::record_batch::RecordBatch;
 use chrono::NaiveDateTime;
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use csv::Writer;
 use once_cell::sync::Lazy;
-use regex::Regex;
+use parquet::arrow::ArrowWriter;
+use parquet::file::properties::WriterProperties;
+use regex::{Captures, Regex};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::error::Error;
 use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, IsTerminal, Write};
 use std::path::PathBuf;
+use std::sync::Arc;
 
 // Statically compiled regular expressions for efficient parsing of log lines.
 static RE_TIME: Lazy<Regex> = Lazy::new(|| Regex::new(r"^# Time: (.*)").unwrap());
@@ -22,6 +33,11 @@ static RE_USER_HOST: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^# User@Host: (.*?) @\s*(.*)").unwrap());
 static RE_METADATA_1: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^# Thread_id: (\d+)\s+Schema: (.*?)\s+QC_hit: (\S+)").unwrap());
+// Percona Server/Percona Toolkit's variant of the thread/schema line: it has no `QC_hit` field,
+// carrying `Last_errno`/`Killed` instead, so it needs its own pattern rather than reusing
+// RE_METADATA_1 and leaving `thread_id`/`schema` unpopulated for every Percona entry.
+static RE_METADATA_PERCONA_THREAD: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^# Thread_id: (\d+)\s+Schema: (\S*)\s+Last_errno: \d+\s+Killed: \d+").unwrap());
 static RE_METADATA_2: Lazy<Regex> = Lazy::new(|| {
     Regex::new(
         r"^# Query_time: ([\d.]+)\s+Lock_time: ([\d.]+)\s+Rows_sent: (\d+)\s+Rows_examined: (\d+)",
@@ -59,6 +75,71 @@ static RE_SKIPPED_1: Lazy<Regex> = Lazy::new(|| Regex::new(r"started with:\s*$")
 static RE_SKIPPED_2: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^((Tcp port:)|(Time\s+Id\s+Command))").unwrap());
 
+// Regexes used to normalize a query into a statement fingerprint for --aggregate.
+// Quoted string literals (single or double quoted, with backslash escapes) collapse to `?`.
+static RE_STRING_LIT: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"'(?:\\.|[^'\\])*'|"(?:\\.|[^"\\])*""#).unwrap());
+// Numeric literals (including decimals and hex) collapse to `?`.
+static RE_NUM_LIT: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\b0x[0-9a-fA-F]+\b|\b\d+\.?\d*\b").unwrap());
+// An `IN (?, ?, ?)` list collapses to a single `IN (?)`.
+static RE_IN_LIST: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?i)\bIN\s*\(\s*\?(?:\s*,\s*\?)*\s*\)").unwrap());
+// Comparison operators are padded to exactly one space on each side, so `id=7` and `id = 7`
+// fold to the same fingerprint. Multi-character operators are listed before their single-character
+// prefixes so the alternation doesn't stop short (e.g. `<=` before `<`).
+static RE_OPERATOR_SPACING: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\s*(<=|>=|<>|!=|=|<|>)\s*").unwrap());
+// Runs of whitespace collapse to a single space.
+static RE_WHITESPACE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\s+").unwrap());
+// The SQL keywords we fold to lower-case so that shape-identical queries share a fingerprint
+// regardless of the casing the application happened to use.
+static RE_KEYWORDS: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?i)\b(SELECT|INSERT|UPDATE|DELETE|REPLACE|FROM|WHERE|AND|OR|NOT|NULL|IN|IS|LIKE|BETWEEN|JOIN|INNER|LEFT|RIGHT|OUTER|CROSS|ON|GROUP|ORDER|BY|HAVING|LIMIT|OFFSET|ASC|DESC|DISTINCT|AS|SET|VALUES|INTO|UNION|ALL|EXISTS|CASE|WHEN|THEN|ELSE|END|COUNT|SUM|AVG|MIN|MAX)\b",
+    )
+    .unwrap()
+});
+
+// Matches each `Key: value` pair on a `#` comment line, where a value runs up to the next
+// `Key:` token (a whitespace-separated token ending in `:`) or the end of the line; the captured
+// value may carry trailing whitespace. Used to discover dialect-specific fields generically.
+static RE_COMMENT_KV: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(\w+):\s*((?:\S*[^\s:](?:\s+|$))*)").unwrap());
+
+// The comment keys that already map to a typed `SlowQueryEntry` column. Pairs discovered by
+// `RE_COMMENT_KV` with one of these keys are left to the typed handlers; everything else is
+// captured into `SlowQueryEntry::extra`.
+static KNOWN_KEYS: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+    [
+        "Time",
+        "User",
+        "Host",
+        "Thread_id",
+        "Schema",
+        "QC_hit",
+        "Query_time",
+        "Lock_time",
+        "Rows_sent",
+        "Rows_examined",
+        "Rows_affected",
+        "Bytes_sent",
+        "Tmp_tables",
+        "Tmp_disk_tables",
+        "Tmp_table_sizes",
+        "Full_scan",
+        "Full_join",
+        "Tmp_table",
+        "Tmp_table_on_disk",
+        "Filesort",
+        "Filesort_on_disk",
+        "Merge_passes",
+        "Priority_queue",
+    ]
+    .into_iter()
+    .collect()
+});
+
 /// Represents a single entry from the slow query log.
 #[derive(Debug, Default, Clone)]
 struct SlowQueryEntry {
@@ -86,12 +167,23 @@ struct SlowQueryEntry {
     filesort_on_disk: String,
     merge_passes: u64,
     priority_queue: String,
+    /// Dialect-specific `# key: value` comment fields that do not map to a known column, captured
+    /// dynamically and surfaced as extra CSV columns.
+    extra: BTreeMap<String, String>,
+    /// Set once the entry's `# User@Host` header has been parsed. Every dialect emits this line
+    /// for every entry, which makes it a reliable completeness proxy; the typed metadata fields
+    /// below it (e.g. `thread_id`) are not, since which ones a dialect populates varies.
+    header_seen: bool,
 }
 
 impl SlowQueryEntry {
-    /// Writes the contents of the struct as a single record to a CSV writer.
-    /// This function also performs the logic to split the query column.
-    fn write_to_csv<W: Write>(&self, wtr: &mut Writer<W>) -> Result<(), Box<dyn Error>> {
+    /// Builds the ordered list of CSV fields for this entry, performing the logic to split the
+    /// query column into its `SET timestamp`, `use schema` and remaining-statement parts. The
+    /// columns included vary by `dialect`, matching [`csv_header_for`]: MariaDB emits the full set
+    /// of typed metadata columns, Percona adds `thread_id`/`schema` to the common core, and MySQL
+    /// emits only the common core, since those are the columns each dialect's handler table
+    /// actually populates.
+    fn to_record(&self, dialect: Dialect) -> Vec<String> {
         // Use string slices to avoid unnecessary clones
         let query = &self.query;
 
@@ -110,7 +202,6 @@ impl SlowQueryEntry {
         };
 
         // 3. Process the query: extract remaining content after removing extracted statements
-        // let mut single_line_query = String::with_capacity(query.len());
         let mut remaining_query = query.to_string();
 
         // Remove SET timestamp statement if found
@@ -127,54 +218,765 @@ impl SlowQueryEntry {
             remaining_query = format!("{before}{after}");
         }
 
-        // Process remaining query: single pass with minimal allocations
-        // let mut first = true;
-        // for line in remaining_query.lines() {
-        //     let trimmed = line.trim();
-        //     if !trimmed.is_empty() {
-        //         if !first {
-        //             single_line_query.push(' ');
-        //         }
-        //         single_line_query.push_str(trimmed);
-        //         first = false;
-        //     }
-        // }
-
-        wtr.write_record([
-            &self.time,
-            &self.user,
-            &self.host,
-            &self.thread_id,
-            &self.schema,
-            &self.qc_hit,
-            &set_timestamp_str,
-            &use_schema_str,
-            &remaining_query,
-            &self.query_time.to_string(),
-            &self.lock_time.to_string(),
-            &self.rows_sent.to_string(),
-            &self.rows_examined.to_string(),
-            &self.rows_affected.to_string(),
-            &self.bytes_sent.to_string(),
-            &self.tmp_tables.to_string(),
-            &self.tmp_disk_tables.to_string(),
-            &self.tmp_table_sizes.to_string(),
-            &self.full_scan,
-            &self.full_join,
-            &self.tmp_table,
-            &self.tmp_table_on_disk,
-            &self.filesort,
-            &self.filesort_on_disk,
-            &self.merge_passes.to_string(),
-            &self.priority_queue,
-        ])?;
-        Ok(())
+        let mut record = vec![self.time.clone(), self.user.clone(), self.host.clone()];
+        match dialect {
+            Dialect::Mariadb => {
+                record.push(self.thread_id.clone());
+                record.push(self.schema.clone());
+                record.push(self.qc_hit.clone());
+            }
+            Dialect::Percona => {
+                record.push(self.thread_id.clone());
+                record.push(self.schema.clone());
+            }
+            Dialect::Mysql => {}
+        }
+        record.push(set_timestamp_str);
+        record.push(use_schema_str);
+        record.push(remaining_query);
+        record.push(self.query_time.to_string());
+        record.push(self.lock_time.to_string());
+        record.push(self.rows_sent.to_string());
+        record.push(self.rows_examined.to_string());
+        record.push(self.rows_affected.to_string());
+        record.push(self.bytes_sent.to_string());
+        if dialect == Dialect::Mariadb {
+            record.push(self.tmp_tables.to_string());
+            record.push(self.tmp_disk_tables.to_string());
+            record.push(self.tmp_table_sizes.to_string());
+            record.push(self.full_scan.clone());
+            record.push(self.full_join.clone());
+            record.push(self.tmp_table.clone());
+            record.push(self.tmp_table_on_disk.clone());
+            record.push(self.filesort.clone());
+            record.push(self.filesort_on_disk.clone());
+            record.push(self.merge_passes.to_string());
+            record.push(self.priority_queue.clone());
+        }
+        record
     }
 
-    /// Checks if the entry has enough data to be considered a valid, writeable record.
-    /// We use thread_id as a proxy for a complete metadata block.
+    /// Checks if the entry has enough data to be considered a valid, writeable record. A
+    /// completed `# User@Host` header is the proxy every dialect shares; the typed metadata
+    /// fields below it (e.g. `thread_id`) are not a reliable proxy since MySQL/Percona grammars
+    /// don't always populate them.
     fn is_valid(&self) -> bool {
-        !self.thread_id.is_empty()
+        self.header_seen
+    }
+}
+
+/// Normalizes a query body into a statement fingerprint: application-supplied literals are
+/// stripped out so that queries differing only in their parameter values collapse together.
+/// The `SET timestamp` and `use` preamble statements are removed first, then string and numeric
+/// literals become `?`, `IN (...)` lists collapse, comparison operators are padded to a single
+/// space on each side, whitespace is squeezed, and SQL keywords are lower-cased.
+fn fingerprint(query: &str) -> String {
+    let without_preamble = RE_USE_SCHEMA_EXTRACT
+        .replace_all(&RE_SET_TIMESTAMP_EXTRACT.replace_all(query, " "), " ");
+    let no_strings = RE_STRING_LIT.replace_all(&without_preamble, "?");
+    let no_numbers = RE_NUM_LIT.replace_all(&no_strings, "?");
+    let collapsed_in = RE_IN_LIST.replace_all(&no_numbers, "IN (?)");
+    let spaced_operators = RE_OPERATOR_SPACING.replace_all(&collapsed_in, " $1 ");
+    let squeezed = RE_WHITESPACE.replace_all(&spaced_operators, " ");
+    let folded = RE_KEYWORDS.replace_all(&squeezed, |caps: &Captures| caps[0].to_lowercase());
+    folded.trim().to_string()
+}
+
+/// Returns a stable 64-bit hash of a fingerprint, used as the group key for aggregation.
+fn fingerprint_hash(fingerprint: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    fingerprint.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Picks the percentile value from an already-sorted slice using the nearest-rank method:
+/// the value at index `ceil(p/100 * n) - 1`, clamped to the slice bounds.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let n = sorted.len();
+    let rank = (p / 100.0 * n as f64).ceil() as usize;
+    let idx = rank.saturating_sub(1).min(n - 1);
+    sorted[idx]
+}
+
+/// Accumulated statistics for a single statement fingerprint in `--aggregate` mode.
+#[derive(Debug, Default)]
+struct Digest {
+    /// The first normalized query text seen for this group, kept as a human-readable sample.
+    sample: String,
+    query_times: Vec<f64>,
+    sum_query_time: f64,
+    rows_sent: u64,
+    rows_examined: u64,
+    bytes_sent: u64,
+}
+
+impl Digest {
+    /// Folds one entry into the running totals for its fingerprint group.
+    fn record(&mut self, sample: &str, entry: &SlowQueryEntry) {
+        if self.query_times.is_empty() {
+            self.sample = sample.to_string();
+        }
+        self.query_times.push(entry.query_time);
+        self.sum_query_time += entry.query_time;
+        self.rows_sent += entry.rows_sent;
+        self.rows_examined += entry.rows_examined;
+        self.bytes_sent += entry.bytes_sent;
+    }
+}
+
+/// Parses a standard "yyyy-mm-dd HH:MM:SS" timestamp, the canonical form produced by
+/// [`format_log_time`] and stored in [`SlowQueryEntry::time`]. `--since`/`--until` bounds are
+/// parsed the same way so they compare directly against an entry's carried-forward time.
+fn parse_datetime(s: &str) -> Result<NaiveDateTime, chrono::ParseError> {
+    NaiveDateTime::parse_from_str(s.trim(), "%Y-%m-%d %H:%M:%S")
+}
+
+/// A comparison operator accepted inside a `--filter` expression.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Gt,
+    Lt,
+    Ge,
+    Le,
+    Eq,
+    Ne,
+}
+
+/// A parsed `--filter` predicate tree. Comparisons are combined by `AND`/`OR`, with `AND`
+/// binding more tightly than `OR`.
+#[derive(Debug)]
+enum Predicate {
+    Compare {
+        field: String,
+        op: CmpOp,
+        value: String,
+    },
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+}
+
+impl Predicate {
+    /// Evaluates the predicate against a single entry. An unknown field, or an operator that does
+    /// not apply to the field's type, evaluates to `false` so malformed clauses never let an
+    /// entry through by accident.
+    fn eval(&self, entry: &SlowQueryEntry) -> bool {
+        match self {
+            Predicate::And(a, b) => a.eval(entry) && b.eval(entry),
+            Predicate::Or(a, b) => a.eval(entry) || b.eval(entry),
+            Predicate::Compare { field, op, value } => {
+                if let Some(lhs) = numeric_field(entry, field) {
+                    let Ok(rhs) = value.parse::<f64>() else {
+                        return false;
+                    };
+                    match op {
+                        CmpOp::Gt => lhs > rhs,
+                        CmpOp::Lt => lhs < rhs,
+                        CmpOp::Ge => lhs >= rhs,
+                        CmpOp::Le => lhs <= rhs,
+                        CmpOp::Eq => lhs == rhs,
+                        CmpOp::Ne => lhs != rhs,
+                    }
+                } else if let Some(lhs) = string_field(entry, field) {
+                    match op {
+                        CmpOp::Eq => lhs == value,
+                        CmpOp::Ne => lhs != value,
+                        _ => false,
+                    }
+                } else {
+                    false
+                }
+            }
+        }
+    }
+}
+
+/// Returns the value of a numeric `SlowQueryEntry` field by name, or `None` if the name is not a
+/// numeric field.
+fn numeric_field(entry: &SlowQueryEntry, field: &str) -> Option<f64> {
+    match field {
+        "query_time" => Some(entry.query_time),
+        "lock_time" => Some(entry.lock_time),
+        "rows_sent" => Some(entry.rows_sent as f64),
+        "rows_examined" => Some(entry.rows_examined as f64),
+        "rows_affected" => Some(entry.rows_affected as f64),
+        "bytes_sent" => Some(entry.bytes_sent as f64),
+        _ => None,
+    }
+}
+
+/// Returns the value of a string `SlowQueryEntry` field by name, or `None` if the name is not a
+/// string field supported by `--filter`.
+fn string_field<'a>(entry: &'a SlowQueryEntry, field: &str) -> Option<&'a str> {
+    match field {
+        "schema" => Some(&entry.schema),
+        "user" => Some(&entry.user),
+        "host" => Some(&entry.host),
+        _ => None,
+    }
+}
+
+/// Parses a `--filter` expression such as
+/// `query_time > 2.0 AND schema = reporting OR rows_examined > 100000` into a [`Predicate`] tree.
+/// Tokens must be whitespace-separated. `AND` binds more tightly than `OR`.
+fn parse_filter(expr: &str) -> Result<Predicate, String> {
+    let tokens: Vec<&str> = expr.split_whitespace().collect();
+    if tokens.is_empty() {
+        return Err("empty filter expression".to_string());
+    }
+    let mut pos = 0;
+    let pred = parse_or(&tokens, &mut pos)?;
+    if pos != tokens.len() {
+        return Err(format!("unexpected token `{}` in filter", tokens[pos]));
+    }
+    Ok(pred)
+}
+
+fn parse_or(tokens: &[&str], pos: &mut usize) -> Result<Predicate, String> {
+    let mut left = parse_and(tokens, pos)?;
+    while *pos < tokens.len() && tokens[*pos].eq_ignore_ascii_case("OR") {
+        *pos += 1;
+        let right = parse_and(tokens, pos)?;
+        left = Predicate::Or(Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_and(tokens: &[&str], pos: &mut usize) -> Result<Predicate, String> {
+    let mut left = parse_comparison(tokens, pos)?;
+    while *pos < tokens.len() && tokens[*pos].eq_ignore_ascii_case("AND") {
+        *pos += 1;
+        let right = parse_comparison(tokens, pos)?;
+        left = Predicate::And(Box::new(left), Box::new(right));
+    }
+    Ok(left)
+}
+
+fn parse_comparison(tokens: &[&str], pos: &mut usize) -> Result<Predicate, String> {
+    let field = tokens
+        .get(*pos)
+        .ok_or_else(|| "expected a field name in filter".to_string())?;
+    let op_tok = tokens
+        .get(*pos + 1)
+        .ok_or_else(|| format!("expected an operator after `{field}` in filter"))?;
+    let value = tokens
+        .get(*pos + 2)
+        .ok_or_else(|| format!("expected a value after `{field} {op_tok}` in filter"))?;
+    let op = match *op_tok {
+        ">" => CmpOp::Gt,
+        "<" => CmpOp::Lt,
+        ">=" => CmpOp::Ge,
+        "<=" => CmpOp::Le,
+        "=" => CmpOp::Eq,
+        "!=" => CmpOp::Ne,
+        other => return Err(format!("unknown operator `{other}` in filter")),
+    };
+    *pos += 3;
+    Ok(Predicate::Compare {
+        field: field.to_string(),
+        op,
+        value: value.to_string(),
+    })
+}
+
+/// A real-world slow-log dialect, selecting which handler table and header set the parser uses.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum Dialect {
+    Mysql,
+    Mariadb,
+    Percona,
+}
+
+/// A handler that folds one matched metadata comment line into the entry being built.
+type MetaHandler = fn(&mut SlowQueryEntry, &Captures);
+
+fn handle_thread_schema(entry: &mut SlowQueryEntry, caps: &Captures) {
+    entry.thread_id = caps.get(1).map_or("", |m| m.as_str()).to_string();
+    entry.schema = caps.get(2).map_or("", |m| m.as_str()).trim().to_string();
+    entry.qc_hit = caps.get(3).map_or("", |m| m.as_str()).trim().to_string();
+}
+
+fn handle_percona_thread_schema(entry: &mut SlowQueryEntry, caps: &Captures) {
+    entry.thread_id = caps.get(1).map_or("", |m| m.as_str()).to_string();
+    entry.schema = caps.get(2).map_or("", |m| m.as_str()).trim().to_string();
+}
+
+fn handle_query_time(entry: &mut SlowQueryEntry, caps: &Captures) {
+    entry.query_time = caps
+        .get(1)
+        .map_or(0.0, |m| m.as_str().parse().unwrap_or(0.0));
+    entry.lock_time = caps
+        .get(2)
+        .map_or(0.0, |m| m.as_str().parse().unwrap_or(0.0));
+    entry.rows_sent = caps.get(3).map_or(0, |m| m.as_str().parse().unwrap_or(0));
+    entry.rows_examined = caps.get(4).map_or(0, |m| m.as_str().parse().unwrap_or(0));
+}
+
+fn handle_rows_affected(entry: &mut SlowQueryEntry, caps: &Captures) {
+    entry.rows_affected = caps.get(1).map_or(0, |m| m.as_str().parse().unwrap_or(0));
+    entry.bytes_sent = caps.get(2).map_or(0, |m| m.as_str().parse().unwrap_or(0));
+}
+
+fn handle_tmp_tables(entry: &mut SlowQueryEntry, caps: &Captures) {
+    entry.tmp_tables = caps.get(1).map_or(0, |m| m.as_str().parse().unwrap_or(0));
+    entry.tmp_disk_tables = caps.get(2).map_or(0, |m| m.as_str().parse().unwrap_or(0));
+    entry.tmp_table_sizes = caps.get(3).map_or(0, |m| m.as_str().parse().unwrap_or(0));
+}
+
+fn handle_full_scan(entry: &mut SlowQueryEntry, caps: &Captures) {
+    entry.full_scan = caps.get(1).map_or("", |m| m.as_str()).trim().to_string();
+    entry.full_join = caps.get(2).map_or("", |m| m.as_str()).trim().to_string();
+    entry.tmp_table = caps.get(3).map_or("", |m| m.as_str()).trim().to_string();
+    entry.tmp_table_on_disk = caps.get(4).map_or("", |m| m.as_str()).trim().to_string();
+}
+
+fn handle_filesort(entry: &mut SlowQueryEntry, caps: &Captures) {
+    entry.filesort = caps.get(1).map_or("", |m| m.as_str()).trim().to_string();
+    entry.filesort_on_disk = caps.get(2).map_or("", |m| m.as_str()).trim().to_string();
+    entry.merge_passes = caps.get(3).map_or(0, |m| m.as_str().parse().unwrap_or(0));
+    entry.priority_queue = caps.get(4).map_or("", |m| m.as_str()).trim().to_string();
+}
+
+/// Returns the ordered `(regex, handler)` table for a dialect. MariaDB emits the full set of
+/// `# Thread_id/Full_scan/Filesort` comment blocks; MySQL and Percona emit a smaller fixed set,
+/// and any key not in [`KNOWN_KEYS`] (InnoDB stats, `Bytes_received`, future fields, …) is
+/// captured generically into [`SlowQueryEntry::extra`]. A key in `KNOWN_KEYS` is never captured
+/// there, even under a dialect whose table does not populate it (e.g. `Full_scan` under MySQL or
+/// Percona), so its value does not appear in the output.
+fn grammar_for(dialect: Dialect) -> Vec<(&'static Regex, MetaHandler)> {
+    match dialect {
+        Dialect::Mariadb => vec![
+            (&RE_METADATA_1, handle_thread_schema as MetaHandler),
+            (&RE_METADATA_2, handle_query_time),
+            (&RE_METADATA_3, handle_rows_affected),
+            (&RE_METADATA_4, handle_tmp_tables),
+            (&RE_METADATA_5, handle_full_scan),
+            (&RE_METADATA_6, handle_filesort),
+        ],
+        Dialect::Mysql => vec![
+            (&RE_METADATA_2, handle_query_time as MetaHandler),
+            (&RE_METADATA_3, handle_rows_affected),
+        ],
+        Dialect::Percona => vec![
+            (&RE_METADATA_PERCONA_THREAD, handle_percona_thread_schema as MetaHandler),
+            (&RE_METADATA_2, handle_query_time),
+            (&RE_METADATA_3, handle_rows_affected),
+        ],
+    }
+}
+
+/// Returns the per-entry CSV header for a dialect, matching the columns [`SlowQueryEntry::to_record`]
+/// emits for that dialect: MariaDB gets the full typed metadata set, Percona adds `thread_id`/
+/// `schema` to the common core, and MySQL emits only the common core, since its handler table
+/// populates neither.
+fn csv_header_for(dialect: Dialect) -> Vec<&'static str> {
+    let mut header = vec!["time", "user", "host"];
+    match dialect {
+        Dialect::Mariadb => header.extend(["thread_id", "schema", "qc_hit"]),
+        Dialect::Percona => header.extend(["thread_id", "schema"]),
+        Dialect::Mysql => {}
+    }
+    header.extend([
+        "set_timestamp",
+        "use_schema",
+        "query",
+        "query_time",
+        "lock_time",
+        "rows_sent",
+        "rows_examined",
+        "rows_affected",
+        "bytes_sent",
+    ]);
+    if dialect == Dialect::Mariadb {
+        header.extend([
+            "tmp_tables",
+            "tmp_disk_tables",
+            "tmp_table_sizes",
+            "full_scan",
+            "full_join",
+            "tmp_table",
+            "tmp_table_on_disk",
+            "filesort",
+            "filesort_on_disk",
+            "merge_passes",
+            "priority_queue",
+        ]);
+    }
+    header
+}
+
+/// Returns the per-entry CSV header as owned strings, for paths that serialize records by hand.
+fn csv_header_record(dialect: Dialect) -> Vec<String> {
+    csv_header_for(dialect).iter().map(|s| s.to_string()).collect()
+}
+
+/// The column header emitted for `--aggregate` output.
+const AGGREGATE_HEADER: [&str; 13] = [
+    "fingerprint",
+    "query",
+    "count",
+    "sum_query_time",
+    "avg",
+    "min",
+    "max",
+    "p50",
+    "p95",
+    "p99",
+    "rows_sent",
+    "rows_examined",
+    "bytes_sent",
+];
+
+/// The output container format selected with `--format`.
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+enum OutputFormat {
+    /// One CSV row per entry (the default, suitable for spreadsheets).
+    Csv,
+    /// Columnar Apache Parquet, with the low-cardinality string columns dictionary-encoded.
+    Parquet,
+}
+
+/// Builds the Arrow schema mirroring [`SlowQueryEntry`]: `f64` for the timing fields, `u64` for
+/// the counters, `Utf8` for the free-text fields and dictionary-encoded `Utf8` for the
+/// low-cardinality string columns (`schema`, `user`, `host`, `qc_hit`, `full_scan`, `full_join`,
+/// `filesort`, `priority_queue`) whose values repeat heavily across a slow log.
+fn parquet_schema() -> Schema {
+    let dict = || DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+    Schema::new(vec![
+        Field::new("time", DataType::Utf8, false),
+        Field::new("user", dict(), false),
+        Field::new("host", dict(), false),
+        Field::new("thread_id", DataType::Utf8, false),
+        Field::new("schema", dict(), false),
+        Field::new("qc_hit", dict(), false),
+        Field::new("query_time", DataType::Float64, false),
+        Field::new("lock_time", DataType::Float64, false),
+        Field::new("rows_sent", DataType::UInt64, false),
+        Field::new("rows_examined", DataType::UInt64, false),
+        Field::new("rows_affected", DataType::UInt64, false),
+        Field::new("bytes_sent", DataType::UInt64, false),
+        Field::new("query", DataType::Utf8, false),
+        Field::new("tmp_tables", DataType::UInt64, false),
+        Field::new("tmp_disk_tables", DataType::UInt64, false),
+        Field::new("tmp_table_sizes", DataType::UInt64, false),
+        Field::new("full_scan", dict(), false),
+        Field::new("full_join", dict(), false),
+        Field::new("tmp_table", DataType::Utf8, false),
+        Field::new("tmp_table_on_disk", DataType::Utf8, false),
+        Field::new("filesort", dict(), false),
+        Field::new("filesort_on_disk", DataType::Utf8, false),
+        Field::new("merge_passes", DataType::UInt64, false),
+        Field::new("priority_queue", dict(), false),
+    ])
+}
+
+/// Buffers entries into row-group-sized batches and writes them to a Parquet file. Each pushed
+/// entry is cloned into the buffer; each flush becomes one Parquet row group, with the
+/// low-cardinality string columns dictionary-encoded.
+struct ParquetSink {
+    writer: ArrowWriter<Box<dyn Write + Send>>,
+    schema: Arc<Schema>,
+    buffer: Vec<SlowQueryEntry>,
+    batch_size: usize,
+    written: usize,
+}
+
+impl ParquetSink {
+    fn new(writer: Box<dyn Write + Send>, batch_size: usize) -> Result<Self, Box<dyn Error>> {
+        let schema = Arc::new(parquet_schema());
+        let props = WriterProperties::builder()
+            .set_max_row_group_size(batch_size.max(1))
+            .build();
+        let writer = ArrowWriter::try_new(writer, schema.clone(), Some(props))?;
+        Ok(ParquetSink {
+            writer,
+            schema,
+            buffer: Vec::with_capacity(batch_size),
+            batch_size,
+            written: 0,
+        })
+    }
+
+    fn push(&mut self, entry: &SlowQueryEntry) -> Result<(), Box<dyn Error>> {
+        self.buffer.push(entry.clone());
+        if self.buffer.len() >= self.batch_size {
+            self.flush_batch()?;
+        }
+        Ok(())
+    }
+
+    /// Turns the buffered entries into a [`RecordBatch`] and writes it as one row group.
+    fn flush_batch(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let rows = &self.buffer;
+        let utf8 = |get: &dyn Fn(&SlowQueryEntry) -> &str| -> ArrayRef {
+            let mut b = StringBuilder::new();
+            for e in rows {
+                b.append_value(get(e));
+            }
+            Arc::new(b.finish())
+        };
+        let dict = |get: &dyn Fn(&SlowQueryEntry) -> &str| -> Result<ArrayRef, Box<dyn Error>> {
+            let mut b = StringDictionaryBuilder::<Int32Type>::new();
+            for e in rows {
+                b.append(get(e))?;
+            }
+            Ok(Arc::new(b.finish()))
+        };
+        let f64col = |get: &dyn Fn(&SlowQueryEntry) -> f64| -> ArrayRef {
+            let mut b = Float64Builder::new();
+            for e in rows {
+                b.append_value(get(e));
+            }
+            Arc::new(b.finish())
+        };
+        let u64col = |get: &dyn Fn(&SlowQueryEntry) -> u64| -> ArrayRef {
+            let mut b = UInt64Builder::new();
+            for e in rows {
+                b.append_value(get(e));
+            }
+            Arc::new(b.finish())
+        };
+
+        let columns: Vec<ArrayRef> = vec![
+            utf8(&|e| &e.time),
+            dict(&|e| &e.user)?,
+            dict(&|e| &e.host)?,
+            utf8(&|e| &e.thread_id),
+            dict(&|e| &e.schema)?,
+            dict(&|e| &e.qc_hit)?,
+            f64col(&|e| e.query_time),
+            f64col(&|e| e.lock_time),
+            u64col(&|e| e.rows_sent),
+            u64col(&|e| e.rows_examined),
+            u64col(&|e| e.rows_affected),
+            u64col(&|e| e.bytes_sent),
+            utf8(&|e| &e.query),
+            u64col(&|e| e.tmp_tables),
+            u64col(&|e| e.tmp_disk_tables),
+            u64col(&|e| e.tmp_table_sizes),
+            dict(&|e| &e.full_scan)?,
+            dict(&|e| &e.full_join)?,
+            utf8(&|e| &e.tmp_table),
+            utf8(&|e| &e.tmp_table_on_disk),
+            dict(&|e| &e.filesort)?,
+            utf8(&|e| &e.filesort_on_disk),
+            u64col(&|e| e.merge_passes),
+            dict(&|e| &e.priority_queue)?,
+        ];
+
+        let batch = RecordBatch::try_new(self.schema.clone(), columns)?;
+        self.written += batch.num_rows();
+        self.writer.write(&batch)?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<usize, Box<dyn Error>> {
+        self.flush_batch()?;
+        self.writer.close()?;
+        Ok(self.written)
+    }
+}
+
+/// ANSI reset sequence appended after a colorized entry.
+const ANSI_RESET: &str = "\x1b[0m";
+
+/// Picks the ANSI colour sequence for an entry based on its query time: green under 1s, yellow
+/// between 1s and 5s, red above 5s, so the worst queries stand out when following a live log.
+fn latency_color(query_time: f64) -> &'static str {
+    if query_time > 5.0 {
+        "\x1b[31m" // red
+    } else if query_time >= 1.0 {
+        "\x1b[33m" // yellow
+    } else {
+        "\x1b[32m" // green
+    }
+}
+
+/// Serializes one already-split record into a single CSV line (without a trailing newline), using
+/// the same non-numeric quoting as the streaming writer. Used by the colorized follow output,
+/// which needs the row bytes in hand to wrap them in ANSI colour codes.
+fn serialize_record(fields: &[String]) -> Result<String, Box<dyn Error>> {
+    let mut wtr = csv::WriterBuilder::new()
+        .quote_style(csv::QuoteStyle::NonNumeric)
+        .from_writer(vec![]);
+    wtr.write_record(fields)?;
+    wtr.flush()?;
+    let bytes = wtr.into_inner()?;
+    Ok(String::from_utf8(bytes)?.trim_end().to_string())
+}
+
+/// Where converted entries are sent. In the default mode each entry is streamed straight to CSV;
+/// in `--aggregate` mode entries are folded into per-fingerprint digests that are emitted on
+/// [`Sink::finish`]; with `--format parquet` they are buffered into columnar row-group batches;
+/// the colorized variant is used when following a live log on a TTY.
+enum Sink {
+    /// Streams rows straight to the writer as they're pushed. `extra_keys` is the fixed,
+    /// already-known set of [`SlowQueryEntry::extra`] columns to append after the typed ones —
+    /// for the default batch path this comes from a pre-parse scan of the input (see
+    /// `scan_extra_keys`); `--follow` always uses an empty set since the input isn't fully known
+    /// up front. `follow` gates a per-entry flush, since `--follow` never reaches `finish()` and
+    /// the csv crate's internal buffer would otherwise hold entries back from a piped/redirected
+    /// reader for a long time.
+    Csv {
+        wtr: Writer<Box<dyn Write + Send>>,
+        dialect: Dialect,
+        extra_keys: Vec<String>,
+        follow: bool,
+    },
+    ColorCsv {
+        writer: Box<dyn Write + Send>,
+        dialect: Dialect,
+    },
+    Aggregate {
+        wtr: Writer<Box<dyn Write + Send>>,
+        groups: HashMap<u64, Digest>,
+    },
+    Parquet(ParquetSink),
+}
+
+impl Sink {
+    /// Builds the sink for the requested mode and writes its header row (Parquet carries its schema
+    /// in the file footer written on close, so it emits no header up front).
+    fn new(
+        writer: Box<dyn Write + Send>,
+        aggregate: bool,
+        format: OutputFormat,
+        batch_size: usize,
+        color: bool,
+        dialect: Dialect,
+        extra_keys: Vec<String>,
+        follow: bool,
+    ) -> Result<Self, Box<dyn Error>> {
+        if !aggregate && format == OutputFormat::Parquet {
+            return Ok(Sink::Parquet(ParquetSink::new(writer, batch_size)?));
+        }
+        if !aggregate && color {
+            // Emit the header uncolored, then colorize each subsequent entry.
+            let mut writer = writer;
+            writeln!(writer, "{}", serialize_record(&csv_header_record(dialect))?)?;
+            return Ok(Sink::ColorCsv { writer, dialect });
+        }
+        if !aggregate {
+            let mut wtr = csv::WriterBuilder::new()
+                .quote_style(csv::QuoteStyle::NonNumeric)
+                .from_writer(writer);
+            let mut header = csv_header_record(dialect);
+            header.extend(extra_keys.iter().cloned());
+            wtr.write_record(&header)?;
+            return Ok(Sink::Csv {
+                wtr,
+                dialect,
+                extra_keys,
+                follow,
+            });
+        }
+        let mut wtr = csv::WriterBuilder::new()
+            .quote_style(csv::QuoteStyle::NonNumeric)
+            .from_writer(writer);
+        wtr.write_record(AGGREGATE_HEADER)?;
+        Ok(Sink::Aggregate {
+            wtr,
+            groups: HashMap::new(),
+        })
+    }
+
+    /// Consumes one completed entry, either writing it immediately or folding it into a digest.
+    fn push(&mut self, entry: &SlowQueryEntry) -> Result<(), Box<dyn Error>> {
+        match self {
+            Sink::Csv {
+                wtr,
+                dialect,
+                extra_keys,
+                follow,
+            } => {
+                let mut record = entry.to_record(*dialect);
+                for key in extra_keys.iter() {
+                    record.push(entry.extra.get(key).cloned().unwrap_or_default());
+                }
+                wtr.write_record(&record)?;
+                if *follow {
+                    wtr.flush()?;
+                }
+                Ok(())
+            }
+            Sink::ColorCsv { writer, dialect } => {
+                let line = serialize_record(&entry.to_record(*dialect))?;
+                let color = latency_color(entry.query_time);
+                writeln!(writer, "{color}{line}{ANSI_RESET}")?;
+                writer.flush()?;
+                Ok(())
+            }
+            Sink::Aggregate { groups, .. } => {
+                let sample = fingerprint(&entry.query);
+                let key = fingerprint_hash(&sample);
+                groups.entry(key).or_default().record(&sample, entry);
+                Ok(())
+            }
+            Sink::Parquet(sink) => sink.push(entry),
+        }
+    }
+
+    /// Flushes any buffered output. Returns the number of Parquet rows or aggregate groups
+    /// written; the streaming CSV sinks do not count their rows and return 0.
+    fn finish(self) -> Result<usize, Box<dyn Error>> {
+        match self {
+            Sink::Parquet(sink) => sink.finish(),
+            Sink::ColorCsv { mut writer, .. } => {
+                writer.flush()?;
+                Ok(0)
+            }
+            Sink::Csv { mut wtr, .. } => {
+                wtr.flush()?;
+                Ok(0)
+            }
+            Sink::Aggregate { mut wtr, groups } => {
+                let count = groups.len();
+                // Order the output by total time descending so the heaviest shapes come first.
+                let mut digests: Vec<(u64, Digest)> = groups.into_iter().collect();
+                digests.sort_by(|a, b| {
+                    b.1.sum_query_time
+                        .partial_cmp(&a.1.sum_query_time)
+                        .unwrap_or(std::cmp::Ordering::Equal)
+                });
+                for (key, mut digest) in digests {
+                    digest
+                        .query_times
+                        .sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+                    let n = digest.query_times.len();
+                    let avg = if n > 0 {
+                        digest.sum_query_time / n as f64
+                    } else {
+                        0.0
+                    };
+                    let min = digest.query_times.first().copied().unwrap_or(0.0);
+                    let max = digest.query_times.last().copied().unwrap_or(0.0);
+                    wtr.write_record([
+                        &key.to_string(),
+                        &digest.sample,
+                        &n.to_string(),
+                        &digest.sum_query_time.to_string(),
+                        &avg.to_string(),
+                        &min.to_string(),
+                        &max.to_string(),
+                        &percentile(&digest.query_times, 50.0).to_string(),
+                        &percentile(&digest.query_times, 95.0).to_string(),
+                        &percentile(&digest.query_times, 99.0).to_string(),
+                        &digest.rows_sent.to_string(),
+                        &digest.rows_examined.to_string(),
+                        &digest.bytes_sent.to_string(),
+                    ])?;
+                }
+                wtr.flush()?;
+                Ok(count)
+            }
+        }
     }
 }
 
@@ -194,6 +996,83 @@ struct Args {
     /// Path for the output CSV file. If omitted, output will be sent to stdout.
     #[arg(short, long)]
     output: Option<PathBuf>,
+
+    /// Aggregate entries by normalized statement fingerprint, emitting one row per query shape
+    /// with count, summed/averaged query time, latency percentiles (p50/p95/p99) and summed
+    /// row/byte counters instead of one row per log entry.
+    #[arg(long)]
+    aggregate: bool,
+
+    /// Skip entries whose carried-forward time is before this "yyyy-mm-dd HH:MM:SS" bound.
+    #[arg(long)]
+    since: Option<String>,
+
+    /// Skip entries whose carried-forward time is after this "yyyy-mm-dd HH:MM:SS" bound.
+    #[arg(long)]
+    until: Option<String>,
+
+    /// Keep only entries matching a predicate expression, e.g.
+    /// `query_time > 2.0 AND schema = reporting`. Numeric fields (query_time, lock_time,
+    /// rows_sent, rows_examined, rows_affected, bytes_sent) support `> < >= <= =`; string fields
+    /// (schema, user, host) support `= !=`; clauses combine with `AND`/`OR`.
+    #[arg(long)]
+    filter: Option<String>,
+
+    /// Output container format. `parquet` writes columnar output with the low-cardinality string
+    /// columns dictionary-encoded; ignored when `--aggregate` is set.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Csv)]
+    format: OutputFormat,
+
+    /// Number of rows buffered per Parquet row group before flushing.
+    #[arg(long, default_value_t = 65536)]
+    row_group_size: usize,
+
+    /// Keep the input file open and emit entries as they are appended, like `tail -f`, instead of
+    /// exiting at end of file.
+    #[arg(long)]
+    follow: bool,
+
+    /// Disable ANSI colorization of followed entries even when writing to a TTY.
+    #[arg(long)]
+    no_color: bool,
+
+    /// Slow-log dialect, selecting the metadata handler table and the CSV column set (the Parquet
+    /// schema is the same for every dialect). Unknown `# Key: value` comment fields are added as
+    /// extra columns to the batch CSV output under any dialect; `--follow`, `--aggregate` and
+    /// Parquet output do not include them.
+    #[arg(long, value_enum, default_value_t = Dialect::Mariadb)]
+    dialect: Dialect,
+}
+
+/// Scans the input file once ahead of the real parse to discover the set of dialect-specific
+/// `# key: value` comment fields that will land in [`SlowQueryEntry::extra`], so the default batch
+/// CSV path can write a complete header and stream rows afterwards instead of buffering every
+/// entry to compute the extra-column union at the end.
+fn scan_extra_keys(path: &std::path::Path) -> Result<BTreeSet<String>, Box<dyn Error>> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut keys = BTreeSet::new();
+    for line in reader.lines() {
+        let line = line?;
+        // Mirror process_line's branch order: `# Time: ...` and `# User@Host: ...` are each
+        // routed to their own dedicated regex there and never reach the generic comment sweep, so
+        // skip them here too. Otherwise a MySQL `# User@Host: ... Id: 8` header would yield an
+        // `Id` key under RE_COMMENT_KV, injecting a spurious always-empty `Id` column.
+        if !line.starts_with('#')
+            || RE_SKIPPED_1.is_match(&line)
+            || RE_SKIPPED_2.is_match(&line)
+            || RE_TIME.is_match(&line)
+            || RE_USER_HOST.is_match(&line)
+        {
+            continue;
+        }
+        for caps in RE_COMMENT_KV.captures_iter(&line) {
+            let key = caps.get(1).map_or("", |m| m.as_str());
+            if !KNOWN_KEYS.contains(key) {
+                keys.insert(key.to_string());
+            }
+        }
+    }
+    Ok(keys)
 }
 
 /// Parses MariaDB's log time format ("yymmdd H:M:S") into a standard
@@ -204,13 +1083,96 @@ fn format_log_time(log_time: &str) -> Result<String, chrono::ParseError> {
     Ok(dt.format("%Y-%m-%d %H:%M:%S").to_string())
 }
 
+/// Mutable parser state carried across log lines. When a new `User@Host` header is seen the
+/// previous entry is completed and flushed, so a finished entry only materializes when the next
+/// one begins (or, in batch mode, at end of file).
+struct ParseState {
+    current_entry: SlowQueryEntry,
+    last_seen_time: String,
+    entry_count: usize,
+}
+
+/// Feeds one log line through the parser, updating `state` and flushing a completed entry to the
+/// sink when a new header starts it. `keep` gates which completed entries are emitted.
+fn process_line(
+    line: &str,
+    state: &mut ParseState,
+    sink: &mut Sink,
+    grammar: &[(&'static Regex, MetaHandler)],
+    keep: &dyn Fn(&SlowQueryEntry) -> bool,
+) -> Result<(), Box<dyn Error>> {
+    if RE_SKIPPED_1.is_match(line) || RE_SKIPPED_2.is_match(line) {
+        return Ok(());
+    }
+
+    if let Some(caps) = RE_TIME.captures(line) {
+        let raw_time = caps.get(1).map_or("", |m| m.as_str()).trim();
+        state.last_seen_time = format_log_time(raw_time).unwrap_or_else(|_| raw_time.to_string());
+    } else if let Some(caps) = RE_USER_HOST.captures(line) {
+        if state.current_entry.is_valid() && keep(&state.current_entry) {
+            sink.push(&state.current_entry)?;
+            state.entry_count += 1;
+        }
+        state.current_entry = SlowQueryEntry {
+            time: state.last_seen_time.clone(),
+            header_seen: true,
+            ..Default::default()
+        };
+        let user_full = caps.get(1).map_or("", |m| m.as_str()).trim();
+        state.current_entry.user = user_full.split('[').next().unwrap_or("").to_string();
+        let host_full = caps.get(2).map_or("", |m| m.as_str()).trim();
+        state.current_entry.host = host_full
+            .trim_matches(|c| c == '[' || c == ']' || c == ' ')
+            .to_string();
+    } else if line.starts_with('#') {
+        // A metadata comment line. Run the dialect's typed handlers first, then sweep the line
+        // for any `Key: value` pairs the handlers did not consume and keep them as extra columns.
+        for (re, handler) in grammar {
+            if let Some(caps) = re.captures(line) {
+                handler(&mut state.current_entry, &caps);
+                break;
+            }
+        }
+        for caps in RE_COMMENT_KV.captures_iter(line) {
+            let key = caps.get(1).map_or("", |m| m.as_str());
+            if !KNOWN_KEYS.contains(key) {
+                let value = caps.get(2).map_or("", |m| m.as_str()).trim().to_string();
+                state
+                    .current_entry
+                    .extra
+                    .insert(key.to_string(), value);
+            }
+        }
+    } else if !line.trim().is_empty() {
+        // Pre-allocate capacity for better performance
+        if state.current_entry.query.is_empty() {
+            state.current_entry.query.reserve(line.len() + 1);
+        }
+        state.current_entry.query.push_str(line);
+        state.current_entry.query.push('\n');
+    }
+    Ok(())
+}
+
 /// Main function to orchestrate the file reading, parsing, and writing.
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
+
+    // `--follow` never reaches EOF, so sinks that only emit on `finish()` (the Parquet writer's
+    // footer, the aggregate digest) would otherwise hang indefinitely and never produce valid
+    // output. Reject the combination up front instead of writing a truncated/empty file.
+    if args.follow && args.format == OutputFormat::Parquet {
+        return Err("--follow cannot be combined with --format parquet: Parquet requires a closing footer that is only written at end of file".into());
+    }
+    if args.follow && args.aggregate {
+        return Err("--follow cannot be combined with --aggregate: the digest is only emitted at end of file".into());
+    }
+
     eprintln!("Starting conversion...");
     eprintln!("Input file: {}", args.input.display());
 
-    let writer: Box<dyn Write> = match args.output {
+    let to_file = args.output.is_some();
+    let writer: Box<dyn Write + Send> = match &args.output {
         Some(path) => {
             eprintln!("Output file: {}", path.display());
             Box::new(File::create(path)?)
@@ -218,127 +1180,299 @@ fn main() -> Result<(), Box<dyn Error>> {
         None => Box::new(std::io::stdout()),
     };
 
+    // Colorize only when following a live log whose output is a terminal, unless overridden.
+    let color = args.follow && !args.no_color && !to_file && std::io::stdout().is_terminal();
+
+    // The default batch CSV path pre-scans the input for its dynamically-discovered extra columns
+    // so rows can stream straight to the writer afterwards instead of buffering the whole file in
+    // memory. `--follow` can't pre-scan a log that is still being appended to, so it keeps the
+    // fixed, extra-column-free header it always has.
+    let extra_keys: Vec<String> = if !args.follow && !args.aggregate && args.format == OutputFormat::Csv {
+        scan_extra_keys(&args.input)?.into_iter().collect()
+    } else {
+        Vec::new()
+    };
+
     let input_file = File::open(&args.input)?;
-    let reader = BufReader::new(input_file);
+    let mut reader = BufReader::new(input_file);
 
-    let mut wtr = csv::WriterBuilder::new()
-        .quote_style(csv::QuoteStyle::NonNumeric)
-        .from_writer(writer);
-
-    // Write the updated header row to the CSV file.
-    wtr.write_record([
-        "time",
-        "user",
-        "host",
-        "thread_id",
-        "schema",
-        "qc_hit",
-        "set_timestamp",
-        "use_schema",
-        "query",
-        "query_time",
-        "lock_time",
-        "rows_sent",
-        "rows_examined",
-        "rows_affected",
-        "bytes_sent",
-        "tmp_tables",
-        "tmp_disk_tables",
-        "tmp_table_sizes",
-        "full_scan",
-        "full_join",
-        "tmp_table",
-        "tmp_table_on_disk",
-        "filesort",
-        "filesort_on_disk",
-        "merge_passes",
-        "priority_queue",
-    ])?;
-
-    let mut current_entry = SlowQueryEntry::default();
-    let mut last_seen_time = String::new();
-    let mut entry_count = 0;
+    let mut sink = Sink::new(
+        writer,
+        args.aggregate,
+        args.format,
+        args.row_group_size,
+        color,
+        args.dialect,
+        extra_keys,
+        args.follow,
+    )?;
+
+    // Select the metadata handler table for the requested dialect.
+    let grammar = grammar_for(args.dialect);
+
+    // Parse the optional time-window bounds and predicate once, up front.
+    let since = args.since.as_deref().map(parse_datetime).transpose()?;
+    let until = args.until.as_deref().map(parse_datetime).transpose()?;
+    let filter = args.filter.as_deref().map(parse_filter).transpose()?;
+
+    // Decides whether a completed entry survives the `--since`/`--until` window and `--filter`
+    // predicate. An entry with an unparseable time is never dropped on time grounds alone.
+    let keep = |entry: &SlowQueryEntry| -> bool {
+        if since.is_some() || until.is_some() {
+            if let Ok(dt) = parse_datetime(&entry.time) {
+                if let Some(s) = since {
+                    if dt < s {
+                        return false;
+                    }
+                }
+                if let Some(u) = until {
+                    if dt > u {
+                        return false;
+                    }
+                }
+            }
+        }
+        filter.as_ref().map(|p| p.eval(entry)).unwrap_or(true)
+    };
+
+    let mut state = ParseState {
+        current_entry: SlowQueryEntry::default(),
+        last_seen_time: String::new(),
+        entry_count: 0,
+    };
+
+    if args.follow {
+        // Tail the file: read whatever is available, then wait past EOF for more to be appended.
+        let mut buf = String::new();
+        loop {
+            buf.clear();
+            let bytes = reader.read_line(&mut buf)?;
+            if bytes == 0 {
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                continue;
+            }
+            let line = buf.trim_end_matches(['\r', '\n']);
+            process_line(line, &mut state, &mut sink, &grammar, &keep)?;
+        }
+    }
 
     for line_result in reader.lines() {
         let line = line_result?;
+        process_line(&line, &mut state, &mut sink, &grammar, &keep)?;
+    }
 
-        if RE_SKIPPED_1.is_match(&line) || RE_SKIPPED_2.is_match(&line) {
-            continue;
+    if state.current_entry.is_valid() && keep(&state.current_entry) {
+        sink.push(&state.current_entry)?;
+        state.entry_count += 1;
+    }
+
+    let entry_count = state.entry_count;
+    let emitted = sink.finish()?;
+
+    if args.aggregate {
+        eprintln!(
+            "\nSuccess! Aggregated {entry_count} slow query entries into {emitted} statement shapes."
+        );
+    } else {
+        eprintln!("\nSuccess! Converted {entry_count} slow query entries.");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fingerprint_collapses_literals_and_case() {
+        let a = fingerprint("SELECT * FROM users WHERE id = 42 AND name = 'alice'");
+        let b = fingerprint("select   *  from users where id=7 and name='bob'");
+        assert_eq!(a, b);
+        assert_eq!(a, "select * from users where id = ? and name = ?");
+    }
+
+    #[test]
+    fn fingerprint_collapses_in_lists() {
+        let f = fingerprint("SELECT * FROM t WHERE id IN (1, 2, 3)");
+        // RE_KEYWORDS runs after the IN-list collapse, so `IN` gets lower-cased like every other
+        // keyword.
+        assert_eq!(f, "select * from t where id in (?)");
+    }
+
+    #[test]
+    fn fingerprint_strips_preamble() {
+        let f = fingerprint("SET timestamp=1700000000;\nuse `mydb`;\nSELECT 1");
+        assert_eq!(f, "select ?");
+    }
+
+    #[test]
+    fn percentile_nearest_rank_indices() {
+        let sorted = vec![10.0, 20.0, 30.0, 40.0, 50.0];
+        // ceil(0.5 * 5) - 1 = 2 -> 30.0
+        assert_eq!(percentile(&sorted, 50.0), 30.0);
+        // ceil(0.95 * 5) - 1 = 4 -> 50.0
+        assert_eq!(percentile(&sorted, 95.0), 50.0);
+        // ceil(0.99 * 5) - 1 = 4 -> 50.0
+        assert_eq!(percentile(&sorted, 99.0), 50.0);
+    }
+
+    #[test]
+    fn percentile_empty_slice_is_zero() {
+        assert_eq!(percentile(&[], 50.0), 0.0);
+    }
+
+    fn entry_for_filter() -> SlowQueryEntry {
+        SlowQueryEntry {
+            query_time: 3.5,
+            rows_examined: 200_000,
+            schema: "reporting".to_string(),
+            user: "alice".to_string(),
+            ..Default::default()
         }
+    }
 
-        if let Some(caps) = RE_TIME.captures(&line) {
-            let raw_time = caps.get(1).map_or("", |m| m.as_str()).trim();
-            last_seen_time = format_log_time(raw_time).unwrap_or_else(|_| raw_time.to_string());
-        } else if let Some(caps) = RE_USER_HOST.captures(&line) {
-            if current_entry.is_valid() {
-                current_entry.write_to_csv(&mut wtr)?;
-                entry_count += 1;
-            }
-            current_entry = SlowQueryEntry {
-                time: last_seen_time.clone(),
-                ..Default::default()
-            };
-            let user_full = caps.get(1).map_or("", |m| m.as_str()).trim();
-            current_entry.user = user_full.split('[').next().unwrap_or("").to_string();
-            let host_full = caps.get(2).map_or("", |m| m.as_str()).trim();
-            current_entry.host = host_full
-                .trim_matches(|c| c == '[' || c == ']' || c == ' ')
-                .to_string();
-        } else if let Some(caps) = RE_METADATA_1.captures(&line) {
-            current_entry.thread_id = caps.get(1).map_or("", |m| m.as_str()).to_string();
-            current_entry.schema = caps.get(2).map_or("", |m| m.as_str()).trim().to_string();
-            current_entry.qc_hit = caps.get(3).map_or("", |m| m.as_str()).trim().to_string();
-        } else if let Some(caps) = RE_METADATA_2.captures(&line) {
-            current_entry.query_time = caps
-                .get(1)
-                .map_or(0.0, |m| m.as_str().parse().unwrap_or(0.0));
-            current_entry.lock_time = caps
-                .get(2)
-                .map_or(0.0, |m| m.as_str().parse().unwrap_or(0.0));
-            current_entry.rows_sent = caps.get(3).map_or(0, |m| m.as_str().parse().unwrap_or(0));
-            current_entry.rows_examined =
-                caps.get(4).map_or(0, |m| m.as_str().parse().unwrap_or(0));
-        } else if let Some(caps) = RE_METADATA_3.captures(&line) {
-            current_entry.rows_affected =
-                caps.get(1).map_or(0, |m| m.as_str().parse().unwrap_or(0));
-            current_entry.bytes_sent = caps.get(2).map_or(0, |m| m.as_str().parse().unwrap_or(0));
-        } else if let Some(caps) = RE_METADATA_4.captures(&line) {
-            current_entry.tmp_tables = caps.get(1).map_or(0, |m| m.as_str().parse().unwrap_or(0));
-            current_entry.tmp_disk_tables =
-                caps.get(2).map_or(0, |m| m.as_str().parse().unwrap_or(0));
-            current_entry.tmp_table_sizes =
-                caps.get(3).map_or(0, |m| m.as_str().parse().unwrap_or(0));
-        } else if let Some(caps) = RE_METADATA_5.captures(&line) {
-            current_entry.full_scan = caps.get(1).map_or("", |m| m.as_str()).trim().to_string();
-            current_entry.full_join = caps.get(2).map_or("", |m| m.as_str()).trim().to_string();
-            current_entry.tmp_table = caps.get(3).map_or("", |m| m.as_str()).trim().to_string();
-            current_entry.tmp_table_on_disk =
-                caps.get(4).map_or("", |m| m.as_str()).trim().to_string();
-        } else if let Some(caps) = RE_METADATA_6.captures(&line) {
-            current_entry.filesort = caps.get(1).map_or("", |m| m.as_str()).trim().to_string();
-            current_entry.filesort_on_disk =
-                caps.get(2).map_or("", |m| m.as_str()).trim().to_string();
-            current_entry.merge_passes = caps.get(3).map_or(0, |m| m.as_str().parse().unwrap_or(0));
-            current_entry.priority_queue =
-                caps.get(4).map_or("", |m| m.as_str()).trim().to_string();
-        } else if !line.starts_with('#') && !line.trim().is_empty() {
-            // Pre-allocate capacity for better performance
-            if current_entry.query.is_empty() {
-                current_entry.query.reserve(line.len() + 1);
+    #[test]
+    fn parse_datetime_accepts_canonical_format() {
+        let dt = parse_datetime("2024-01-02 03:04:05").unwrap();
+        assert_eq!(dt.to_string(), "2024-01-02 03:04:05");
+    }
+
+    #[test]
+    fn parse_datetime_rejects_malformed_input() {
+        assert!(parse_datetime("not a date").is_err());
+    }
+
+    #[test]
+    fn parse_filter_and_binds_tighter_than_or() {
+        // `a OR b AND c` must parse as `a OR (b AND c)`, so an entry matching only `a` still
+        // passes even though it fails `b`.
+        let pred = parse_filter("schema = reporting OR query_time > 99.0 AND rows_examined > 999999999")
+            .unwrap();
+        assert!(pred.eval(&entry_for_filter()));
+
+        let pred = parse_filter("schema = other OR query_time > 2.0 AND rows_examined > 999999999")
+            .unwrap();
+        assert!(!pred.eval(&entry_for_filter()));
+    }
+
+    #[test]
+    fn parse_filter_combines_numeric_and_string_fields() {
+        let pred =
+            parse_filter("query_time > 2.0 AND schema = reporting AND rows_examined > 100000")
+                .unwrap();
+        assert!(pred.eval(&entry_for_filter()));
+    }
+
+    #[test]
+    fn parse_filter_unknown_field_evaluates_false() {
+        let pred = parse_filter("bogus_field = reporting").unwrap();
+        assert!(!pred.eval(&entry_for_filter()));
+    }
+
+    #[test]
+    fn parse_filter_rejects_malformed_expression() {
+        assert!(parse_filter("query_time >").is_err());
+        assert!(parse_filter("").is_err());
+    }
+
+    #[test]
+    fn parquet_schema_dictionary_encodes_low_cardinality_columns() {
+        let schema = parquet_schema();
+        let dict_type = DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8));
+        for name in [
+            "user",
+            "host",
+            "schema",
+            "qc_hit",
+            "full_scan",
+            "full_join",
+            "filesort",
+            "priority_queue",
+        ] {
+            let field = schema.field_with_name(name).unwrap();
+            assert_eq!(field.data_type(), &dict_type, "{name} should be dictionary-encoded");
+        }
+        // High-cardinality / numeric columns stay plain, not dictionary-encoded.
+        assert_eq!(
+            schema.field_with_name("query_time").unwrap().data_type(),
+            &DataType::Float64
+        );
+        assert_eq!(
+            schema.field_with_name("thread_id").unwrap().data_type(),
+            &DataType::Utf8
+        );
+    }
+
+    #[test]
+    fn latency_color_picks_threshold_by_query_time() {
+        assert_eq!(latency_color(0.5), "\x1b[32m");
+        assert_eq!(latency_color(1.0), "\x1b[33m");
+        assert_eq!(latency_color(4.999), "\x1b[33m");
+        assert_eq!(latency_color(5.0001), "\x1b[31m");
+    }
+
+    fn run_grammar(dialect: Dialect, lines: &[&str]) -> SlowQueryEntry {
+        let grammar = grammar_for(dialect);
+        let mut entry = SlowQueryEntry {
+            header_seen: true,
+            ..Default::default()
+        };
+        for line in lines {
+            for (re, handler) in &grammar {
+                if let Some(caps) = re.captures(line) {
+                    handler(&mut entry, &caps);
+                    break;
+                }
             }
-            current_entry.query.push_str(&line);
-            current_entry.query.push('\n');
         }
+        entry
     }
 
-    if current_entry.is_valid() {
-        current_entry.write_to_csv(&mut wtr)?;
-        entry_count += 1;
+    #[test]
+    fn is_valid_does_not_require_thread_id() {
+        // A bare completed header with no typed metadata at all (e.g. a dialect whose handler
+        // table didn't match anything on this entry) must still count as valid.
+        let entry = SlowQueryEntry {
+            header_seen: true,
+            ..Default::default()
+        };
+        assert!(entry.is_valid());
+        assert!(!SlowQueryEntry::default().is_valid());
     }
 
-    wtr.flush()?;
+    #[test]
+    fn mysql_grammar_populates_query_time_without_thread_id() {
+        let entry = run_grammar(
+            Dialect::Mysql,
+            &["# Query_time: 1.5  Lock_time: 0.0 Rows_sent: 1  Rows_examined: 2"],
+        );
+        assert_eq!(entry.query_time, 1.5);
+        assert!(entry.thread_id.is_empty());
+        assert!(entry.is_valid(), "header_seen, not thread_id, gates validity");
+    }
 
-    eprintln!("\nSuccess! Converted {entry_count} slow query entries.");
+    #[test]
+    fn percona_grammar_matches_thread_line_without_qc_hit() {
+        let entry = run_grammar(
+            Dialect::Percona,
+            &[
+                "# Thread_id: 123  Schema: mydb  Last_errno: 0  Killed: 0",
+                "# Query_time: 2.5  Lock_time: 0.1 Rows_sent: 3  Rows_examined: 4",
+            ],
+        );
+        assert_eq!(entry.thread_id, "123");
+        assert_eq!(entry.schema, "mydb");
+        assert_eq!(entry.query_time, 2.5);
+    }
 
-    Ok(())
+    #[test]
+    fn csv_header_varies_by_dialect() {
+        assert!(csv_header_for(Dialect::Mariadb).contains(&"thread_id"));
+        assert!(csv_header_for(Dialect::Mariadb).contains(&"full_scan"));
+        assert!(!csv_header_for(Dialect::Mysql).contains(&"thread_id"));
+        assert!(!csv_header_for(Dialect::Mysql).contains(&"full_scan"));
+        assert!(csv_header_for(Dialect::Percona).contains(&"thread_id"));
+        assert!(!csv_header_for(Dialect::Percona).contains(&"full_scan"));
+    }
 }